@@ -1,15 +1,24 @@
 use anyhow::{anyhow, Result};
+use chrono::NaiveDate;
 use clap::Parser;
+use flate2::read::GzDecoder;
+use futures_util::StreamExt;
 use indicatif::{ProgressBar, ProgressStyle};
 use quick_xml::de::from_str;
-use reqwest::Client;
+use reqwest::{Client, Response, StatusCode};
+use scraper::{Html, Selector};
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::fs;
+use std::io::Read;
 use std::path::Path;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
-use std::time::Duration;
-use tokio::sync::Semaphore;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::{Mutex, Semaphore};
+use tracing::Instrument;
 use url::Url;
 
 #[derive(Parser)]
@@ -18,22 +27,59 @@ use url::Url;
 struct Args {
     /// URL of the sitemap to analyze
     sitemap_url: String,
-    
+
     /// Number of threads for parallel requests
     #[arg(long, default_value = "10")]
     threads: usize,
-    
+
     /// Output folder
     #[arg(long, default_value = "output")]
     output: String,
-    
+
     /// Save files instead of creating only JSON
     #[arg(long)]
     save_files: bool,
-    
+
     /// Timeout in seconds for individual page requests
     #[arg(long, default_value = "30")]
     timeout: u64,
+
+    /// Follow same-host links discovered in fetched HTML pages
+    #[arg(long)]
+    crawl: bool,
+
+    /// Maximum link-following depth when --crawl is set (sitemap URLs are depth 0)
+    #[arg(long, default_value = "3")]
+    max_depth: usize,
+
+    /// Maximum number of pages to visit when --crawl is set. Sitemap URLs count toward
+    /// it and are always visited; discovered links are queued only while below it.
+    #[arg(long, default_value = "1000")]
+    max_pages: usize,
+
+    /// Maximum number of retries for connection errors, timeouts, 5xx and 429 responses
+    #[arg(long, default_value = "3")]
+    max_retries: u32,
+
+    /// User-Agent header sent with every request, also used to match robots.txt groups
+    #[arg(long, default_value = "sitemap-crawler")]
+    user_agent: String,
+
+    /// Don't fetch or honor robots.txt
+    #[arg(long)]
+    ignore_robots: bool,
+
+    /// Only crawl URLs whose sitemap `lastmod` is on or after this date (YYYY-MM-DD or RFC3339)
+    #[arg(long)]
+    modified_since: Option<String>,
+
+    /// Only crawl URLs whose sitemap `priority` is at least this value
+    #[arg(long)]
+    min_priority: Option<f32>,
+
+    /// Address (host:port) to serve Prometheus metrics on, e.g. 127.0.0.1:9898
+    #[arg(long)]
+    metrics_addr: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -42,9 +88,15 @@ struct Urlset {
     urls: Vec<UrlEntry>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 struct UrlEntry {
     loc: String,
+    #[serde(default)]
+    lastmod: Option<String>,
+    #[serde(default)]
+    changefreq: Option<String>,
+    #[serde(default)]
+    priority: Option<f32>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -56,16 +108,111 @@ struct SitemapIndex {
 #[derive(Debug, Deserialize)]
 struct SitemapEntry {
     loc: String,
+    #[serde(default)]
+    lastmod: Option<String>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct PageResult {
     url: String,
     status_code: u16,
     content_length: usize,
     mime_type: String,
+    attempts: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content_hash: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    etag: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_modified: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sitemap_lastmod: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sitemap_changefreq: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sitemap_priority: Option<f32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     error: Option<String>,
+    /// Set when this run didn't actually fetch the URL (robots.txt disallow, or
+    /// `--modified-since`/`--min-priority` filtering); excluded from `diff_against_previous`
+    /// so a this-run exclusion isn't reported as the URL having disappeared from the sitemap.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    skipped: Option<String>,
+}
+
+/// Loads the `results.json` produced by a previous run, if any, keyed by URL so
+/// the next run can send conditional headers and diff against it.
+fn load_previous_results(output_dir: &str) -> HashMap<String, PageResult> {
+    let path = Path::new(output_dir).join("results.json");
+    let Ok(content) = fs::read_to_string(&path) else {
+        return HashMap::new();
+    };
+    let Ok(results) = serde_json::from_str::<Vec<PageResult>>(&content) else {
+        return HashMap::new();
+    };
+    results.into_iter().map(|r| (r.url.clone(), r)).collect()
+}
+
+#[derive(Debug, Serialize)]
+struct DiffReport {
+    added: Vec<String>,
+    removed: Vec<String>,
+    changed: Vec<String>,
+}
+
+/// Compares this run's results against the previous run's to report URLs that
+/// are newly present, gone, or whose status code / content hash changed.
+fn diff_against_previous(
+    previous: &HashMap<String, PageResult>,
+    current: &[PageResult],
+) -> DiffReport {
+    let current_by_url: HashMap<&str, &PageResult> =
+        current.iter().map(|r| (r.url.as_str(), r)).collect();
+
+    // Entries skipped this run (robots.txt disallow, --modified-since/--min-priority
+    // filtering) were never actually fetched, so they're excluded from added/changed;
+    // their presence in `current_by_url` also keeps them out of `removed`.
+    let mut added: Vec<String> = current_by_url
+        .iter()
+        .filter(|(url, result)| result.skipped.is_none() && !previous.contains_key(**url))
+        .map(|(url, _)| url.to_string())
+        .collect();
+    added.sort();
+
+    let mut removed: Vec<String> = previous
+        .keys()
+        .filter(|url| !current_by_url.contains_key(url.as_str()))
+        .cloned()
+        .collect();
+    removed.sort();
+
+    let mut changed: Vec<String> = previous
+        .iter()
+        .filter_map(|(url, prev)| {
+            let current = current_by_url.get(url.as_str())?;
+            if current.skipped.is_some() {
+                return None;
+            }
+            let status_changed = prev.status_code != current.status_code;
+            let hash_changed = prev.content_hash.is_some()
+                && current.content_hash.is_some()
+                && prev.content_hash != current.content_hash;
+            (status_changed || hash_changed).then(|| url.clone())
+        })
+        .collect();
+    changed.sort();
+
+    DiffReport {
+        added,
+        removed,
+        changed,
+    }
+}
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+fn looks_gzipped(url: &str, content_encoding_gzip: bool, body: &[u8]) -> bool {
+    url.ends_with(".gz") || content_encoding_gzip || body.starts_with(&GZIP_MAGIC)
 }
 
 async fn fetch_sitemap(client: &Client, url: &str) -> Result<String> {
@@ -73,25 +220,51 @@ async fn fetch_sitemap(client: &Client, url: &str) -> Result<String> {
     if !response.status().is_success() {
         return Err(anyhow!("Failed to fetch sitemap: {}", response.status()));
     }
-    Ok(response.text().await?)
+
+    let content_encoding_gzip = response
+        .headers()
+        .get("content-encoding")
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.eq_ignore_ascii_case("gzip"));
+    let body = response.bytes().await?;
+
+    if looks_gzipped(url, content_encoding_gzip, &body) {
+        let mut decompressed = String::new();
+        GzDecoder::new(&body[..])
+            .read_to_string(&mut decompressed)
+            .map_err(|e| anyhow!("Failed to decompress gzipped sitemap: {}", e))?;
+        Ok(decompressed)
+    } else {
+        String::from_utf8(body.to_vec()).map_err(|e| anyhow!("Sitemap is not valid UTF-8: {}", e))
+    }
 }
 
-async fn parse_sitemap_urls(client: &Client, sitemap_url: &str) -> Result<Vec<String>> {
+async fn parse_sitemap_urls(client: &Client, sitemap_url: &str) -> Result<Vec<UrlEntry>> {
     let content = fetch_sitemap(client, sitemap_url).await?;
     let mut all_urls = Vec::new();
-    
+
     // Try to parse as sitemap index first
     if let Ok(sitemap_index) = from_str::<SitemapIndex>(&content) {
-        eprintln!("Found sitemap index with {} sitemaps", sitemap_index.sitemaps.len());
-        
+        tracing::info!("Found sitemap index with {} sitemaps", sitemap_index.sitemaps.len());
+
         for sitemap_entry in sitemap_index.sitemaps {
             match parse_single_sitemap(client, &sitemap_entry.loc).await {
                 Ok(mut urls) => {
-                    eprintln!("Extracted {} URLs from {}", urls.len(), sitemap_entry.loc);
+                    match &sitemap_entry.lastmod {
+                        Some(lastmod) => tracing::info!(
+                            "Extracted {} URLs from {} (lastmod: {})",
+                            urls.len(),
+                            sitemap_entry.loc,
+                            lastmod
+                        ),
+                        None => {
+                            tracing::info!("Extracted {} URLs from {}", urls.len(), sitemap_entry.loc)
+                        }
+                    }
                     all_urls.append(&mut urls);
                 }
                 Err(e) => {
-                    eprintln!("Error parsing sitemap {}: {}", sitemap_entry.loc, e);
+                    tracing::warn!("Error parsing sitemap {}: {}", sitemap_entry.loc, e);
                 }
             }
         }
@@ -99,178 +272,1344 @@ async fn parse_sitemap_urls(client: &Client, sitemap_url: &str) -> Result<Vec<St
         // Try to parse as single sitemap
         all_urls = parse_single_sitemap(client, sitemap_url).await?;
     }
-    
+
     Ok(all_urls)
 }
 
-async fn parse_single_sitemap(client: &Client, sitemap_url: &str) -> Result<Vec<String>> {
+async fn parse_single_sitemap(client: &Client, sitemap_url: &str) -> Result<Vec<UrlEntry>> {
     let content = fetch_sitemap(client, sitemap_url).await?;
-    
+
     let urlset: Urlset = from_str(&content)
         .map_err(|e| anyhow!("Failed to parse sitemap XML: {}", e))?;
-    
-    Ok(urlset.urls.into_iter().map(|entry| entry.loc).collect())
+
+    Ok(urlset.urls)
+}
+
+/// Parses a sitemap `lastmod` value (RFC3339 or a bare `YYYY-MM-DD` date) down to a date.
+fn parse_lastmod_date(value: &str) -> Option<NaiveDate> {
+    chrono::DateTime::parse_from_rfc3339(value)
+        .map(|dt| dt.date_naive())
+        .ok()
+        .or_else(|| NaiveDate::parse_from_str(value, "%Y-%m-%d").ok())
+}
+
+/// Applies the `--modified-since`/`--min-priority` filters to a sitemap entry.
+fn passes_filters(entry: &UrlEntry, modified_since: Option<NaiveDate>, min_priority: Option<f32>) -> bool {
+    if let Some(cutoff) = modified_since {
+        match entry.lastmod.as_deref().and_then(parse_lastmod_date) {
+            Some(lastmod) if lastmod >= cutoff => {}
+            _ => return false,
+        }
+    }
+    if let Some(min_priority) = min_priority {
+        // The sitemap protocol defines 0.5 as the default priority when omitted.
+        let priority = entry.priority.unwrap_or(0.5);
+        if priority < min_priority {
+            return false;
+        }
+    }
+    true
 }
 
 fn url_to_filename(url: &str, used_names: &mut HashSet<String>) -> String {
     let parsed_url = Url::parse(url).unwrap_or_else(|_| Url::parse("http://example.com").unwrap());
-    
-    let mut filename = format!("{}{}", 
+
+    let mut filename = format!("{}{}",
         parsed_url.host_str().unwrap_or("unknown"),
         parsed_url.path()
     );
-    
+
     // Replace slashes with underscores
     filename = filename.replace('/', "_");
-    
+
     // Remove invalid characters for filenames
     filename = filename.chars()
         .map(|c| if c.is_alphanumeric() || c == '_' || c == '-' || c == '.' { c } else { '_' })
         .collect();
-    
+
     // Handle collisions
     let mut final_filename = filename.clone();
     let mut counter = 2;
-    
+
     while used_names.contains(&final_filename) {
         final_filename = format!("{}_{}", filename, counter);
         counter += 1;
     }
-    
+
     used_names.insert(final_filename.clone());
     final_filename
 }
 
-async fn fetch_page(client: &Client, url: &str, output_dir: &str, save_files: bool, used_names: Arc<tokio::sync::Mutex<HashSet<String>>>) -> PageResult {
-    match client.get(url).send().await {
-        Ok(response) => {
-            let status_code = response.status().as_u16();
-            let mime_type = response
-                .headers()
-                .get("content-type")
-                .and_then(|ct| ct.to_str().ok())
-                .unwrap_or("unknown")
-                .to_string();
-            
-            match response.bytes().await {
+/// Extracts same-host `<a href>` links from an HTML page, resolved against `page_url`.
+fn extract_same_host_links(page_url: &Url, body: &str) -> Vec<Url> {
+    let Some(host) = page_url.host_str() else {
+        return Vec::new();
+    };
+    let document = Html::parse_document(body);
+    let selector = match Selector::parse("a[href]") {
+        Ok(selector) => selector,
+        Err(_) => return Vec::new(),
+    };
+
+    document
+        .select(&selector)
+        .filter_map(|el| el.value().attr("href"))
+        .filter_map(|href| page_url.join(href).ok())
+        .filter(|link| link.host_str() == Some(host))
+        .map(|mut link| {
+            link.set_fragment(None);
+            link
+        })
+        .collect()
+}
+
+/// Allow/Disallow rules and crawl-delay selected from a robots.txt for one user agent.
+#[derive(Debug, Clone, Default)]
+struct RobotsRules {
+    disallow: Vec<String>,
+    allow: Vec<String>,
+    crawl_delay: Option<f64>,
+}
+
+/// A parsed robots.txt: the rules applicable to our user agent plus any
+/// `Sitemap:` directives, which apply regardless of user agent.
+#[derive(Debug, Clone, Default)]
+struct ParsedRobots {
+    rules: RobotsRules,
+    sitemaps: Vec<String>,
+}
+
+/// Parses robots.txt content, selecting the most specific `User-agent` group that
+/// matches `user_agent` (falling back to `*`) and collecting all `Sitemap:` lines.
+fn parse_robots_txt(content: &str, user_agent: &str) -> ParsedRobots {
+    struct Group {
+        agents: Vec<String>,
+        rules: RobotsRules,
+    }
+
+    let mut groups: Vec<Group> = Vec::new();
+    let mut sitemaps = Vec::new();
+    let mut current: Option<Group> = None;
+    let mut current_has_directives = false;
+
+    for raw_line in content.lines() {
+        let line = raw_line.split('#').next().unwrap_or("").trim();
+        let Some((field, value)) = line.split_once(':') else {
+            continue;
+        };
+        let field = field.trim().to_ascii_lowercase();
+        let value = value.trim();
+
+        match field.as_str() {
+            "user-agent" => {
+                if current.is_none() || current_has_directives {
+                    if let Some(group) = current.take() {
+                        groups.push(group);
+                    }
+                    current = Some(Group {
+                        agents: Vec::new(),
+                        rules: RobotsRules::default(),
+                    });
+                    current_has_directives = false;
+                }
+                current.as_mut().unwrap().agents.push(value.to_ascii_lowercase());
+            }
+            "disallow" => {
+                if let Some(group) = current.as_mut() {
+                    if !value.is_empty() {
+                        group.rules.disallow.push(value.to_string());
+                    }
+                    current_has_directives = true;
+                }
+            }
+            "allow" => {
+                if let Some(group) = current.as_mut() {
+                    if !value.is_empty() {
+                        group.rules.allow.push(value.to_string());
+                    }
+                    current_has_directives = true;
+                }
+            }
+            "crawl-delay" => {
+                if let Some(group) = current.as_mut() {
+                    group.rules.crawl_delay = value.parse::<f64>().ok();
+                    current_has_directives = true;
+                }
+            }
+            "sitemap" => sitemaps.push(value.to_string()),
+            _ => {}
+        }
+    }
+    if let Some(group) = current.take() {
+        groups.push(group);
+    }
+
+    // The group whose product token is the longest (most specific) match against our
+    // user agent wins, not the first one in the file.
+    let agent_lower = user_agent.to_ascii_lowercase();
+    let best_token_len = |agents: &[String]| -> Option<usize> {
+        agents
+            .iter()
+            .filter(|a| a.as_str() != "*" && agent_lower.contains(a.as_str()))
+            .map(|a| a.len())
+            .max()
+    };
+    let selected = groups
+        .iter()
+        .filter_map(|g| best_token_len(&g.agents).map(|len| (len, g)))
+        .max_by_key(|(len, _)| *len)
+        .map(|(_, g)| g)
+        .or_else(|| groups.iter().find(|g| g.agents.iter().any(|a| a == "*")));
+
+    ParsedRobots {
+        rules: selected.map(|g| g.rules.clone()).unwrap_or_default(),
+        sitemaps,
+    }
+}
+
+/// Resolves `Allow`/`Disallow` by longest-matching-rule-wins (the standard robots.txt
+/// precedence), with ties going to `Allow` since it's the more specific carve-out.
+fn is_disallowed(rules: &RobotsRules, path_and_query: &str) -> bool {
+    let best_disallow = rules
+        .disallow
+        .iter()
+        .filter(|prefix| path_and_query.starts_with(prefix.as_str()))
+        .map(|prefix| prefix.len())
+        .max();
+    let best_allow = rules
+        .allow
+        .iter()
+        .filter(|prefix| path_and_query.starts_with(prefix.as_str()))
+        .map(|prefix| prefix.len())
+        .max();
+    match (best_disallow, best_allow) {
+        (Some(d), Some(a)) => d > a,
+        (Some(_), None) => true,
+        (None, _) => false,
+    }
+}
+
+/// Per-host robots.txt rules plus the timestamp of the last request, used to
+/// space requests out according to `Crawl-delay`.
+struct HostEntry {
+    rules: RobotsRules,
+    sitemaps: Vec<String>,
+    last_request: Mutex<Option<Instant>>,
+}
+
+/// Fetches and caches robots.txt per host, and gates requests on disallow rules
+/// and crawl-delay pacing. A no-op when `ignore_robots` is set.
+struct RobotsManager {
+    client: Client,
+    user_agent: String,
+    ignore_robots: bool,
+    hosts: Mutex<HashMap<String, Arc<HostEntry>>>,
+}
+
+impl RobotsManager {
+    fn new(client: Client, user_agent: String, ignore_robots: bool) -> Self {
+        Self {
+            client,
+            user_agent,
+            ignore_robots,
+            hosts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn host_key(url: &Url) -> String {
+        match url.port() {
+            Some(port) => format!("{}:{}", url.host_str().unwrap_or(""), port),
+            None => url.host_str().unwrap_or("").to_string(),
+        }
+    }
+
+    async fn entry_for(&self, url: &Url) -> Arc<HostEntry> {
+        let key = Self::host_key(url);
+        if let Some(entry) = self.hosts.lock().await.get(&key) {
+            return entry.clone();
+        }
+
+        let robots_url = format!("{}://{}/robots.txt", url.scheme(), key);
+        let parsed = match self.client.get(&robots_url).send().await {
+            Ok(response) if response.status().is_success() => match response.text().await {
+                Ok(body) => parse_robots_txt(&body, &self.user_agent),
+                Err(_) => ParsedRobots::default(),
+            },
+            _ => ParsedRobots::default(),
+        };
+
+        let entry = Arc::new(HostEntry {
+            rules: parsed.rules,
+            sitemaps: parsed.sitemaps,
+            last_request: Mutex::new(None),
+        });
+        self.hosts.lock().await.insert(key, entry.clone());
+        entry
+    }
+
+    /// Sitemaps discovered via this host's robots.txt, which is fetched and cached on
+    /// first use (empty when `ignore_robots` is set).
+    async fn sitemaps_for(&self, url: &Url) -> Vec<String> {
+        if self.ignore_robots {
+            return Vec::new();
+        }
+        self.entry_for(url).await.sitemaps.clone()
+    }
+
+    async fn is_allowed(&self, url: &Url) -> bool {
+        if self.ignore_robots {
+            return true;
+        }
+        let entry = self.entry_for(url).await;
+        let path_and_query = match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_string(),
+        };
+        !is_disallowed(&entry.rules, &path_and_query)
+    }
+
+    /// Sleeps as needed so consecutive requests to `url`'s host respect `Crawl-delay`.
+    async fn wait_for_slot(&self, url: &Url) {
+        if self.ignore_robots {
+            return;
+        }
+        let entry = self.entry_for(url).await;
+        let Some(delay) = entry.rules.crawl_delay.filter(|d| *d > 0.0) else {
+            return;
+        };
+        let delay = Duration::from_secs_f64(delay);
+
+        let mut last_request = entry.last_request.lock().await;
+        if let Some(previous) = *last_request {
+            let elapsed = previous.elapsed();
+            if elapsed < delay {
+                tokio::time::sleep(delay - elapsed).await;
+            }
+        }
+        *last_request = Some(Instant::now());
+    }
+}
+
+/// Upper bounds (in seconds) of the request-duration histogram buckets, Prometheus-style.
+const DURATION_BUCKETS: &[f64] = &[0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0];
+
+/// Prometheus counters/gauges/histogram tracking crawl progress, updated as each
+/// `fetch_page` call completes. Exposed in text format by `serve_metrics`.
+struct Metrics {
+    requests_total: AtomicU64,
+    status_counts: Mutex<HashMap<u16, u64>>,
+    bytes_downloaded_total: AtomicU64,
+    retries_total: AtomicU64,
+    duration_bucket_counts: Vec<AtomicU64>,
+    duration_sum_millis: AtomicU64,
+    duration_count: AtomicU64,
+    threads: usize,
+    semaphore: Arc<Semaphore>,
+}
+
+impl Metrics {
+    fn new(threads: usize, semaphore: Arc<Semaphore>) -> Self {
+        Self {
+            requests_total: AtomicU64::new(0),
+            status_counts: Mutex::new(HashMap::new()),
+            bytes_downloaded_total: AtomicU64::new(0),
+            retries_total: AtomicU64::new(0),
+            duration_bucket_counts: (0..=DURATION_BUCKETS.len()).map(|_| AtomicU64::new(0)).collect(),
+            duration_sum_millis: AtomicU64::new(0),
+            duration_count: AtomicU64::new(0),
+            threads,
+            semaphore,
+        }
+    }
+
+    async fn record(&self, status_code: u16, bytes: u64, attempts: u32, duration: Duration) {
+        self.requests_total.fetch_add(1, Ordering::Relaxed);
+        *self.status_counts.lock().await.entry(status_code).or_insert(0) += 1;
+        self.bytes_downloaded_total.fetch_add(bytes, Ordering::Relaxed);
+        self.retries_total
+            .fetch_add(u64::from(attempts.saturating_sub(1)), Ordering::Relaxed);
+
+        let bucket_index = DURATION_BUCKETS
+            .iter()
+            .position(|bound| duration.as_secs_f64() <= *bound)
+            .unwrap_or(DURATION_BUCKETS.len());
+        self.duration_bucket_counts[bucket_index].fetch_add(1, Ordering::Relaxed);
+        self.duration_sum_millis
+            .fetch_add(duration.as_millis() as u64, Ordering::Relaxed);
+        self.duration_count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Renders all metrics in Prometheus text exposition format.
+    async fn render(&self) -> String {
+        let mut out = String::new();
+
+        out.push_str("# HELP sitemap_crawler_requests_total Total page requests completed\n");
+        out.push_str("# TYPE sitemap_crawler_requests_total counter\n");
+        out.push_str(&format!(
+            "sitemap_crawler_requests_total {}\n",
+            self.requests_total.load(Ordering::Relaxed)
+        ));
+
+        out.push_str("# HELP sitemap_crawler_status_total Page requests completed, by HTTP status code\n");
+        out.push_str("# TYPE sitemap_crawler_status_total counter\n");
+        for (status, count) in self.status_counts.lock().await.iter() {
+            out.push_str(&format!(
+                "sitemap_crawler_status_total{{status=\"{}\"}} {}\n",
+                status, count
+            ));
+        }
+
+        out.push_str("# HELP sitemap_crawler_bytes_downloaded_total Total response bytes downloaded\n");
+        out.push_str("# TYPE sitemap_crawler_bytes_downloaded_total counter\n");
+        out.push_str(&format!(
+            "sitemap_crawler_bytes_downloaded_total {}\n",
+            self.bytes_downloaded_total.load(Ordering::Relaxed)
+        ));
+
+        out.push_str("# HELP sitemap_crawler_retries_total Total retry attempts across all requests\n");
+        out.push_str("# TYPE sitemap_crawler_retries_total counter\n");
+        out.push_str(&format!(
+            "sitemap_crawler_retries_total {}\n",
+            self.retries_total.load(Ordering::Relaxed)
+        ));
+
+        out.push_str("# HELP sitemap_crawler_in_flight_requests Requests currently holding a semaphore permit\n");
+        out.push_str("# TYPE sitemap_crawler_in_flight_requests gauge\n");
+        out.push_str(&format!(
+            "sitemap_crawler_in_flight_requests {}\n",
+            self.threads - self.semaphore.available_permits()
+        ));
+
+        out.push_str("# HELP sitemap_crawler_request_duration_seconds Page request duration in seconds\n");
+        out.push_str("# TYPE sitemap_crawler_request_duration_seconds histogram\n");
+        let mut cumulative = 0u64;
+        for (bound, count) in DURATION_BUCKETS.iter().zip(self.duration_bucket_counts.iter()) {
+            cumulative += count.load(Ordering::Relaxed);
+            out.push_str(&format!(
+                "sitemap_crawler_request_duration_seconds_bucket{{le=\"{}\"}} {}\n",
+                bound, cumulative
+            ));
+        }
+        cumulative += self.duration_bucket_counts[DURATION_BUCKETS.len()].load(Ordering::Relaxed);
+        out.push_str(&format!(
+            "sitemap_crawler_request_duration_seconds_bucket{{le=\"+Inf\"}} {}\n",
+            cumulative
+        ));
+        out.push_str(&format!(
+            "sitemap_crawler_request_duration_seconds_sum {}\n",
+            self.duration_sum_millis.load(Ordering::Relaxed) as f64 / 1000.0
+        ));
+        out.push_str(&format!(
+            "sitemap_crawler_request_duration_seconds_count {}\n",
+            self.duration_count.load(Ordering::Relaxed)
+        ));
+
+        out
+    }
+}
+
+/// Serves Prometheus text-format metrics over plain HTTP on `addr`, answering
+/// every request with the current snapshot regardless of path.
+async fn serve_metrics(addr: String, metrics: Arc<Metrics>) {
+    let listener = match tokio::net::TcpListener::bind(&addr).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            tracing::error!("Failed to bind metrics address {}: {}", addr, e);
+            return;
+        }
+    };
+    tracing::info!("Serving Prometheus metrics on http://{}/metrics", addr);
+
+    loop {
+        let (mut socket, _) = match listener.accept().await {
+            Ok(conn) => conn,
+            Err(_) => continue,
+        };
+        let metrics = metrics.clone();
+
+        tokio::spawn(async move {
+            let mut buf = [0u8; 1024];
+            // Best-effort read of the request; we don't route on path or method.
+            let _ = socket.read(&mut buf).await;
+            let body = metrics.render().await;
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            let _ = socket.write_all(response.as_bytes()).await;
+        });
+    }
+}
+
+const INITIAL_RETRY_DELAY: Duration = Duration::from_secs(1);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+fn is_retryable_status(status: StatusCode) -> bool {
+    status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS
+}
+
+fn is_retryable_error(err: &reqwest::Error) -> bool {
+    err.is_timeout() || err.is_connect()
+}
+
+/// Parses an HTTP-date `Retry-After` value (e.g. `Wed, 21 Oct 2026 07:28:00 GMT`)
+/// into the delay remaining until that instant, per RFC 9110.
+fn parse_http_date_delay(value: &str) -> Option<Duration> {
+    let target = chrono::NaiveDateTime::parse_from_str(value, "%a, %d %b %Y %H:%M:%S GMT")
+        .ok()?
+        .and_utc();
+    (target - chrono::Utc::now()).to_std().ok()
+}
+
+/// Honors a `Retry-After` header (seconds or HTTP-date) when present, otherwise
+/// falls back to the computed backoff delay.
+fn retry_delay(response: &Response, computed: Duration) -> Duration {
+    response
+        .headers()
+        .get("retry-after")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| {
+            value
+                .parse::<u64>()
+                .ok()
+                .map(Duration::from_secs)
+                .or_else(|| parse_http_date_delay(value))
+        })
+        .unwrap_or(computed)
+}
+
+/// Streams `response` to `<output_dir>/<filename>.tmp`, renaming it into place only
+/// once the full body has been written, so a failed download never leaves a
+/// corrupt file behind. Returns the number of bytes written and their SHA-256 hash.
+async fn stream_to_file(response: Response, file_path: &Path) -> Result<(usize, String)> {
+    let tmp_path = file_path.with_file_name(format!(
+        "{}.tmp",
+        file_path.file_name().and_then(|n| n.to_str()).unwrap_or("download")
+    ));
+    let result: Result<(usize, String)> = async {
+        let mut file = tokio::fs::File::create(&tmp_path).await?;
+        let mut stream = response.bytes_stream();
+        let mut hasher = Sha256::new();
+        let mut written = 0usize;
+
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk?;
+            file.write_all(&chunk).await?;
+            hasher.update(&chunk);
+            written += chunk.len();
+        }
+        file.flush().await?;
+        drop(file);
+        tokio::fs::rename(&tmp_path, file_path).await?;
+
+        Ok((written, format!("{:x}", hasher.finalize())))
+    }
+    .await;
+
+    // Don't leave a partial `.tmp` file behind when the download fails.
+    if result.is_err() {
+        let _ = tokio::fs::remove_file(&tmp_path).await;
+    }
+    result
+}
+
+/// Builds the `PageResult` for a seed URL that wasn't fetched this run (robots.txt
+/// disallow, or `--modified-since`/`--min-priority` filtering), reusing the previous
+/// run's recorded state when available so `results.json` doesn't lose its history.
+fn skipped_result(prev: Option<&PageResult>, url: &str, reason: &str) -> PageResult {
+    match prev {
+        Some(prev) => PageResult {
+            skipped: Some(reason.to_string()),
+            ..prev.clone()
+        },
+        None => PageResult {
+            url: url.to_string(),
+            status_code: 0,
+            content_length: 0,
+            mime_type: "unknown".to_string(),
+            attempts: 0,
+            content_hash: None,
+            etag: None,
+            last_modified: None,
+            sitemap_lastmod: None,
+            sitemap_changefreq: None,
+            sitemap_priority: None,
+            error: None,
+            skipped: Some(reason.to_string()),
+        },
+    }
+}
+
+/// Builds the `PageResult` for a `304 Not Modified` response by reusing the
+/// previous run's recorded state, since the body was not resent.
+fn unchanged_result(prev: Option<PageResult>, url: &str, attempts: u32) -> PageResult {
+    match prev {
+        Some(prev) => PageResult {
+            attempts,
+            error: None,
+            skipped: None,
+            ..prev
+        },
+        None => PageResult {
+            url: url.to_string(),
+            status_code: 304,
+            content_length: 0,
+            mime_type: "unknown".to_string(),
+            attempts,
+            content_hash: None,
+            etag: None,
+            last_modified: None,
+            sitemap_lastmod: None,
+            sitemap_changefreq: None,
+            sitemap_priority: None,
+            error: None,
+            skipped: None,
+        },
+    }
+}
+
+async fn fetch_page(
+    client: &Client,
+    url: &str,
+    output_dir: &str,
+    save_files: bool,
+    used_names: Arc<Mutex<HashSet<String>>>,
+    max_retries: u32,
+    prev: Option<PageResult>,
+) -> (PageResult, Option<String>) {
+    let mut attempts = 0u32;
+    let mut delay = INITIAL_RETRY_DELAY;
+
+    loop {
+        attempts += 1;
+        let mut request = client.get(url);
+        if let Some(prev) = &prev {
+            if let Some(etag) = &prev.etag {
+                request = request.header("if-none-match", etag);
+            }
+            if let Some(last_modified) = &prev.last_modified {
+                request = request.header("if-modified-since", last_modified);
+            }
+        }
+
+        let response = match request.send().await {
+            Ok(response) => response,
+            Err(e) => {
+                if attempts <= max_retries && is_retryable_error(&e) {
+                    tokio::time::sleep(delay).await;
+                    delay = (delay * 2).min(MAX_RETRY_DELAY);
+                    continue;
+                }
+                return (
+                    PageResult {
+                        url: url.to_string(),
+                        status_code: 0,
+                        content_length: 0,
+                        mime_type: "unknown".to_string(),
+                        attempts,
+                        content_hash: None,
+                        etag: None,
+                        last_modified: None,
+                        sitemap_lastmod: None,
+                        sitemap_changefreq: None,
+                        sitemap_priority: None,
+                        error: Some(format!("Request failed: {}", e)),
+                        skipped: None,
+                    },
+                    None,
+                );
+            }
+        };
+
+        let status = response.status();
+        if status == StatusCode::NOT_MODIFIED {
+            return (unchanged_result(prev, url, attempts), None);
+        }
+        if attempts <= max_retries && is_retryable_status(status) {
+            let wait = retry_delay(&response, delay);
+            tokio::time::sleep(wait).await;
+            delay = (delay * 2).min(MAX_RETRY_DELAY);
+            continue;
+        }
+
+        return finish_fetch(response, url, output_dir, save_files, used_names, attempts).await;
+    }
+}
+
+/// Downloads the (non-retried) response body, optionally streaming it to disk,
+/// and builds the final `PageResult` plus the HTML body used for link discovery.
+async fn finish_fetch(
+    response: Response,
+    url: &str,
+    output_dir: &str,
+    save_files: bool,
+    used_names: Arc<Mutex<HashSet<String>>>,
+    attempts: u32,
+) -> (PageResult, Option<String>) {
+    let status_code = response.status().as_u16();
+    let mime_type = response
+        .headers()
+        .get("content-type")
+        .and_then(|ct| ct.to_str().ok())
+        .unwrap_or("unknown")
+        .to_string();
+    let etag = response
+        .headers()
+        .get("etag")
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_string());
+    let last_modified = response
+        .headers()
+        .get("last-modified")
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_string());
+    let is_html = mime_type.starts_with("text/html");
+
+    if save_files {
+        let mut names_guard = used_names.lock().await;
+        let filename = url_to_filename(url, &mut names_guard);
+        drop(names_guard);
+        let file_path = Path::new(output_dir).join(&filename);
+
+        // When we also need the body for link discovery, buffer it (HTML pages
+        // are small relative to the assets this streaming path is meant to protect).
+        if is_html {
+            return match response.bytes().await {
                 Ok(content) => {
                     let content_length = content.len();
-                    
-                    if save_files {
-                        let mut names_guard = used_names.lock().await;
-                        let filename = url_to_filename(url, &mut *names_guard);
-                        drop(names_guard);
-                        
-                        let file_path = Path::new(output_dir).join(&filename);
-                        if let Err(e) = fs::write(&file_path, &content) {
-                            return PageResult {
+                    let content_hash = Some(format!("{:x}", Sha256::digest(&content)));
+                    let body = String::from_utf8(content.to_vec()).ok();
+                    if let Err(e) = fs::write(&file_path, &content) {
+                        return (
+                            PageResult {
                                 url: url.to_string(),
                                 status_code,
                                 content_length,
                                 mime_type,
+                                attempts,
+                                content_hash,
+                                etag,
+                                last_modified,
+                                sitemap_lastmod: None,
+                                sitemap_changefreq: None,
+                                sitemap_priority: None,
                                 error: Some(format!("Failed to save file: {}", e)),
-                            };
-                        }
+                                skipped: None,
+                            },
+                            None,
+                        );
                     }
-                    
+                    (
+                        PageResult {
+                            url: url.to_string(),
+                            status_code,
+                            content_length,
+                            mime_type,
+                            attempts,
+                            content_hash,
+                            etag,
+                            last_modified,
+                            sitemap_lastmod: None,
+                            sitemap_changefreq: None,
+                            sitemap_priority: None,
+                            error: None,
+                            skipped: None,
+                        },
+                        body,
+                    )
+                }
+                Err(e) => (
                     PageResult {
                         url: url.to_string(),
                         status_code,
-                        content_length,
+                        content_length: 0,
                         mime_type,
-                        error: None,
-                    }
-                }
-                Err(e) => PageResult {
+                        attempts,
+                        content_hash: None,
+                        etag,
+                        last_modified,
+                        sitemap_lastmod: None,
+                        sitemap_changefreq: None,
+                        sitemap_priority: None,
+                        error: Some(format!("Failed to read response body: {}", e)),
+                        skipped: None,
+                    },
+                    None,
+                ),
+            };
+        }
+
+        return match stream_to_file(response, &file_path).await {
+            Ok((content_length, content_hash)) => (
+                PageResult {
+                    url: url.to_string(),
+                    status_code,
+                    content_length,
+                    mime_type,
+                    attempts,
+                    content_hash: Some(content_hash),
+                    etag,
+                    last_modified,
+                    sitemap_lastmod: None,
+                    sitemap_changefreq: None,
+                    sitemap_priority: None,
+                    error: None,
+                    skipped: None,
+                },
+                None,
+            ),
+            Err(e) => (
+                PageResult {
                     url: url.to_string(),
                     status_code,
                     content_length: 0,
                     mime_type,
-                    error: Some(format!("Failed to read response body: {}", e)),
+                    attempts,
+                    content_hash: None,
+                    etag,
+                    last_modified,
+                    sitemap_lastmod: None,
+                    sitemap_changefreq: None,
+                    sitemap_priority: None,
+                    error: Some(format!("Failed to save file: {}", e)),
+                    skipped: None,
+                },
+                None,
+            ),
+        };
+    }
+
+    match response.bytes().await {
+        Ok(content) => {
+            let content_length = content.len();
+            let content_hash = Some(format!("{:x}", Sha256::digest(&content)));
+            let body = if is_html {
+                String::from_utf8(content.to_vec()).ok()
+            } else {
+                None
+            };
+            (
+                PageResult {
+                    url: url.to_string(),
+                    status_code,
+                    content_length,
+                    mime_type,
+                    attempts,
+                    content_hash,
+                    etag,
+                    last_modified,
+                    sitemap_lastmod: None,
+                    sitemap_changefreq: None,
+                    sitemap_priority: None,
+                    error: None,
+                    skipped: None,
                 },
+                body,
+            )
+        }
+        Err(e) => (
+            PageResult {
+                url: url.to_string(),
+                status_code,
+                content_length: 0,
+                mime_type,
+                attempts,
+                content_hash: None,
+                etag,
+                last_modified,
+                sitemap_lastmod: None,
+                sitemap_changefreq: None,
+                sitemap_priority: None,
+                error: Some(format!("Failed to read response body: {}", e)),
+                skipped: None,
+            },
+            None,
+        ),
+    }
+}
+
+/// The `lastmod`/`changefreq`/`priority` a sitemap declared for a URL, carried
+/// through to `PageResult` for audit output. Links discovered while crawling
+/// HTML pages have no sitemap metadata.
+#[derive(Debug, Clone, Default)]
+struct SitemapMeta {
+    lastmod: Option<String>,
+    changefreq: Option<String>,
+    priority: Option<f32>,
+}
+
+impl From<&UrlEntry> for SitemapMeta {
+    fn from(entry: &UrlEntry) -> Self {
+        Self {
+            lastmod: entry.lastmod.clone(),
+            changefreq: entry.changefreq.clone(),
+            priority: entry.priority,
+        }
+    }
+}
+
+/// Shared state for the worker pool. Workers pop `(url, depth, sitemap metadata)`
+/// items from `queue` until it drains and no other worker has work in flight,
+/// recording visited URLs in `visited` so the same page is never queued twice.
+struct WorkPool {
+    queue: Mutex<VecDeque<(Url, usize, Option<SitemapMeta>)>>,
+    visited: Mutex<HashSet<String>>,
+    in_flight: AtomicUsize,
+    scheduled: AtomicUsize,
+    max_pages: usize,
+    max_depth: usize,
+    crawl: bool,
+}
+
+impl WorkPool {
+    async fn pop(&self) -> Option<(Url, usize, Option<SitemapMeta>)> {
+        loop {
+            if let Some(item) = self.queue.lock().await.pop_front() {
+                self.in_flight.fetch_add(1, Ordering::SeqCst);
+                return Some(item);
+            }
+            if self.in_flight.load(Ordering::SeqCst) == 0 {
+                return None;
             }
+            tokio::time::sleep(Duration::from_millis(10)).await;
         }
-        Err(e) => PageResult {
-            url: url.to_string(),
-            status_code: 0,
-            content_length: 0,
-            mime_type: "unknown".to_string(),
-            error: Some(format!("Request failed: {}", e)),
-        },
+    }
+
+    async fn push_discovered(&self, links: Vec<Url>, depth: usize) {
+        if !self.crawl || depth >= self.max_depth {
+            return;
+        }
+        let mut visited = self.visited.lock().await;
+        let mut queue = self.queue.lock().await;
+        for link in links {
+            if self.scheduled.load(Ordering::SeqCst) >= self.max_pages {
+                break;
+            }
+            let key = link.as_str().to_string();
+            if visited.insert(key) {
+                self.scheduled.fetch_add(1, Ordering::SeqCst);
+                queue.push_back((link, depth + 1, None));
+            }
+        }
+    }
+
+    fn finish(&self) {
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
     }
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    tracing_subscriber::fmt::init();
+
     let args = Args::parse();
-    
+
     // Create output folder
     fs::create_dir_all(&args.output)?;
-    
+
     let client = Client::builder()
         .timeout(Duration::from_secs(args.timeout))
+        .user_agent(&args.user_agent)
         .build()?;
-    
-    eprintln!("Analyzing sitemap: {}", args.sitemap_url);
-    
+
+    let robots = Arc::new(RobotsManager::new(
+        client.clone(),
+        args.user_agent.clone(),
+        args.ignore_robots,
+    ));
+
+    tracing::info!("Analyzing sitemap: {}", args.sitemap_url);
+
     // Extract all URLs from sitemap
-    let urls = parse_sitemap_urls(&client, &args.sitemap_url).await?;
-    eprintln!("Found {} total URLs to process", urls.len());
-    
-    // Setup progress bar
-    let progress = ProgressBar::new(urls.len() as u64);
+    let mut seed_urls = parse_sitemap_urls(&client, &args.sitemap_url).await?;
+
+    // Merge in any additional sitemaps discovered via robots.txt
+    if let Ok(parsed) = Url::parse(&args.sitemap_url) {
+        for extra_sitemap in robots.sitemaps_for(&parsed).await {
+            match parse_single_sitemap(&client, &extra_sitemap).await {
+                Ok(mut urls) => {
+                    tracing::info!(
+                        "Extracted {} URLs from robots.txt sitemap {}",
+                        urls.len(),
+                        extra_sitemap
+                    );
+                    seed_urls.append(&mut urls);
+                }
+                Err(e) => {
+                    tracing::warn!("Error parsing robots.txt sitemap {}: {}", extra_sitemap, e);
+                }
+            }
+        }
+    }
+    let modified_since = args
+        .modified_since
+        .as_deref()
+        .map(|value| {
+            parse_lastmod_date(value)
+                .ok_or_else(|| anyhow!("Invalid --modified-since date: {}", value))
+        })
+        .transpose()?;
+
+    let previous_results = load_previous_results(&args.output);
+    if !previous_results.is_empty() {
+        tracing::info!(
+            "Loaded {} results from a previous run for conditional revalidation",
+            previous_results.len()
+        );
+    }
+
+    // Seed URLs filtered out by --modified-since/--min-priority are recorded as
+    // skipped (not dropped) so diff_against_previous doesn't mistake them for
+    // genuine sitemap removals.
+    let mut seeded_results = Vec::new();
+    let total_found = seed_urls.len();
+    seed_urls.retain(|entry| {
+        if passes_filters(entry, modified_since, args.min_priority) {
+            true
+        } else {
+            seeded_results.push(skipped_result(
+                previous_results.get(&entry.loc),
+                &entry.loc,
+                "filtered-out-by-modified-since-or-min-priority",
+            ));
+            false
+        }
+    });
+    if seed_urls.len() != total_found {
+        tracing::info!(
+            "Found {} URLs total, {} after --modified-since/--min-priority filtering",
+            total_found,
+            seed_urls.len()
+        );
+    } else {
+        tracing::info!("Found {} total URLs to process", seed_urls.len());
+    }
+
+    if args.crawl {
+        tracing::info!(
+            "Crawl mode enabled: following same-host links up to depth {} (max {} pages)",
+            args.max_depth, args.max_pages
+        );
+    }
+
+    let previous_results = Arc::new(previous_results);
+
+    let mut visited = HashSet::new();
+    let mut queue = VecDeque::new();
+    for entry in &seed_urls {
+        let Ok(parsed) = Url::parse(&entry.loc) else {
+            tracing::warn!("Skipping invalid URL: {}", entry.loc);
+            continue;
+        };
+        if !robots.is_allowed(&parsed).await {
+            tracing::warn!("Skipping {} (disallowed by robots.txt)", parsed);
+            seeded_results.push(skipped_result(
+                previous_results.get(&entry.loc),
+                &entry.loc,
+                "disallowed-by-robots-txt",
+            ));
+            continue;
+        }
+        if visited.insert(parsed.as_str().to_string()) {
+            queue.push_back((parsed, 0usize, Some(SitemapMeta::from(entry))));
+        }
+    }
+    let scheduled = queue.len();
+
+    let pool = Arc::new(WorkPool {
+        queue: Mutex::new(queue),
+        visited: Mutex::new(visited),
+        in_flight: AtomicUsize::new(0),
+        scheduled: AtomicUsize::new(scheduled),
+        max_pages: args.max_pages,
+        max_depth: args.max_depth,
+        crawl: args.crawl,
+    });
+
+    // Setup progress bar (length grows as the crawl discovers new pages)
+    let progress = ProgressBar::new(scheduled as u64);
     progress.set_style(
         ProgressStyle::default_bar()
             .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({eta})")
             .unwrap()
             .progress_chars("#>-"),
     );
-    
+
     // Semaphore to limit concurrent requests
     let semaphore = Arc::new(Semaphore::new(args.threads));
-    let used_names = Arc::new(tokio::sync::Mutex::new(HashSet::new()));
-    
-    // Process all URLs in parallel
-    let mut tasks = Vec::new();
-    
-    for url in urls {
+    let used_names = Arc::new(Mutex::new(HashSet::new()));
+    let results = Arc::new(Mutex::new(Vec::new()));
+    let metrics = Arc::new(Metrics::new(args.threads, semaphore.clone()));
+
+    if let Some(addr) = args.metrics_addr.clone() {
+        let metrics = metrics.clone();
+        tokio::spawn(serve_metrics(addr, metrics));
+    }
+
+    let mut workers = Vec::new();
+    for _ in 0..args.threads {
         let client = client.clone();
         let output_dir = args.output.clone();
         let save_files = args.save_files;
         let semaphore = semaphore.clone();
         let used_names = used_names.clone();
         let progress = progress.clone();
-        
-        let task = tokio::spawn(async move {
-            let _permit = semaphore.acquire().await.unwrap();
-            let result = fetch_page(&client, &url, &output_dir, save_files, used_names).await;
-            progress.inc(1);
-            result
-        });
-        
-        tasks.push(task);
+        let pool = pool.clone();
+        let results = results.clone();
+        let max_retries = args.max_retries;
+        let previous_results = previous_results.clone();
+        let robots = robots.clone();
+        let metrics = metrics.clone();
+
+        workers.push(tokio::spawn(async move {
+            while let Some((url, depth, meta)) = pool.pop().await {
+                let span = tracing::info_span!("crawl_page", url = %url, depth);
+                async {
+                    let _permit = semaphore.acquire().await.unwrap();
+                    progress.set_length(pool.scheduled.load(Ordering::SeqCst) as u64);
+
+                    robots.wait_for_slot(&url).await;
+
+                    let prev = previous_results.get(url.as_str()).cloned();
+                    let started_at = Instant::now();
+                    let (mut result, body) = fetch_page(
+                        &client,
+                        url.as_str(),
+                        &output_dir,
+                        save_files,
+                        used_names.clone(),
+                        max_retries,
+                        prev,
+                    )
+                    .await;
+                    metrics
+                        .record(
+                            result.status_code,
+                            result.content_length as u64,
+                            result.attempts,
+                            started_at.elapsed(),
+                        )
+                        .await;
+
+                    if let Some(meta) = meta {
+                        result.sitemap_lastmod = meta.lastmod;
+                        result.sitemap_changefreq = meta.changefreq;
+                        result.sitemap_priority = meta.priority;
+                    }
+
+                    if pool.crawl {
+                        if let Some(html) = body {
+                            let links = extract_same_host_links(&url, &html);
+                            let mut allowed_links = Vec::new();
+                            for link in links {
+                                if robots.is_allowed(&link).await {
+                                    allowed_links.push(link);
+                                }
+                            }
+                            pool.push_discovered(allowed_links, depth).await;
+                        }
+                    }
+
+                    progress.inc(1);
+                    results.lock().await.push(result);
+                    pool.finish();
+                }
+                .instrument(span)
+                .await;
+            }
+        }));
     }
-    
-    // Wait for all tasks
-    let mut results = Vec::new();
-    for task in tasks {
-        results.push(task.await?);
+
+    for worker in workers {
+        worker.await?;
     }
-    
+
     progress.finish_with_message("Completed!");
-    
+
+    let mut results = Arc::try_unwrap(results).unwrap().into_inner();
+    results.append(&mut seeded_results);
+
+    // Diff against the previous run before results.json is overwritten
+    if !previous_results.is_empty() {
+        let diff = diff_against_previous(&previous_results, &results);
+        let diff_path = Path::new(&args.output).join("diff.json");
+        fs::write(&diff_path, serde_json::to_string_pretty(&diff)?)?;
+        tracing::info!(
+            "Diff vs previous run: {} added, {} removed, {} changed (see {})",
+            diff.added.len(),
+            diff.removed.len(),
+            diff.changed.len(),
+            diff_path.display()
+        );
+    }
+
     // Save results to JSON
     let json_path = Path::new(&args.output).join("results.json");
     let json_content = serde_json::to_string_pretty(&results)?;
     fs::write(&json_path, json_content)?;
-    
-    eprintln!("Results saved to: {}", json_path.display());
-    eprintln!("Processed {} URLs", results.len());
-    
+
+    tracing::info!("Results saved to: {}", json_path.display());
+    tracing::info!("Processed {} URLs", results.len());
+
     // Statistics
     let successful = results.iter().filter(|r| r.error.is_none()).count();
     let failed = results.len() - successful;
-    eprintln!("Successful: {}, Failed: {}", successful, failed);
-    
+    tracing::info!("Successful: {}, Failed: {}", successful, failed);
+
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page_result(url: &str) -> PageResult {
+        PageResult {
+            url: url.to_string(),
+            status_code: 200,
+            content_length: 0,
+            mime_type: "text/html".to_string(),
+            attempts: 1,
+            content_hash: None,
+            etag: None,
+            last_modified: None,
+            sitemap_lastmod: None,
+            sitemap_changefreq: None,
+            sitemap_priority: None,
+            error: None,
+            skipped: None,
+        }
+    }
+
+    #[test]
+    fn looks_gzipped_detects_extension_header_and_magic_bytes() {
+        assert!(looks_gzipped("https://example.com/sitemap.xml.gz", false, b""));
+        assert!(looks_gzipped("https://example.com/sitemap.xml", true, b""));
+        assert!(looks_gzipped("https://example.com/sitemap.xml", false, &GZIP_MAGIC));
+        assert!(!looks_gzipped("https://example.com/sitemap.xml", false, b"<urlset>"));
+    }
+
+    #[test]
+    fn passes_filters_defaults_missing_priority_to_half() {
+        let entry = UrlEntry {
+            loc: "https://example.com/".to_string(),
+            lastmod: None,
+            changefreq: None,
+            priority: None,
+        };
+        assert!(passes_filters(&entry, None, Some(0.5)));
+        assert!(!passes_filters(&entry, None, Some(0.6)));
+    }
+
+    #[test]
+    fn passes_filters_rejects_entries_older_than_modified_since() {
+        let cutoff = NaiveDate::from_ymd_opt(2024, 6, 1).unwrap();
+        let stale = UrlEntry {
+            loc: "https://example.com/old".to_string(),
+            lastmod: Some("2024-01-01".to_string()),
+            changefreq: None,
+            priority: None,
+        };
+        let fresh = UrlEntry {
+            loc: "https://example.com/new".to_string(),
+            lastmod: Some("2024-12-31T00:00:00Z".to_string()),
+            changefreq: None,
+            priority: None,
+        };
+        assert!(!passes_filters(&stale, Some(cutoff), None));
+        assert!(passes_filters(&fresh, Some(cutoff), None));
+    }
+
+    #[test]
+    fn parse_robots_txt_selects_most_specific_group() {
+        let content = "\
+User-agent: sitemap-crawler-extended
+Disallow: /wrong/
+
+User-agent: sitemap-crawler
+Disallow: /right/
+
+User-agent: *
+Disallow: /fallback/
+";
+        let parsed = parse_robots_txt(content, "sitemap-crawler");
+        assert_eq!(parsed.rules.disallow, vec!["/right/".to_string()]);
+    }
+
+    #[test]
+    fn is_disallowed_lets_longest_allow_rule_win_over_disallow() {
+        let rules = RobotsRules {
+            disallow: vec!["/".to_string()],
+            allow: vec!["/public/".to_string()],
+            crawl_delay: None,
+        };
+        assert!(is_disallowed(&rules, "/private/page"));
+        assert!(!is_disallowed(&rules, "/public/page"));
+    }
+
+    #[test]
+    fn is_disallowed_ties_favor_allow() {
+        let rules = RobotsRules {
+            disallow: vec!["/foo".to_string()],
+            allow: vec!["/foo".to_string()],
+            crawl_delay: None,
+        };
+        assert!(!is_disallowed(&rules, "/foo"));
+    }
+
+    #[test]
+    fn parse_http_date_delay_computes_duration_until_target() {
+        let future = chrono::Utc::now() + chrono::Duration::seconds(120);
+        let header = future.format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+        let delay = parse_http_date_delay(&header).expect("should parse HTTP-date");
+        assert!(delay.as_secs() > 0 && delay.as_secs() <= 120);
+    }
+
+    #[test]
+    fn parse_http_date_delay_rejects_non_http_date_values() {
+        assert!(parse_http_date_delay("120").is_none());
+        assert!(parse_http_date_delay("not a date").is_none());
+    }
+
+    #[test]
+    fn diff_against_previous_ignores_skipped_entries() {
+        let mut previous = HashMap::new();
+        previous.insert(
+            "https://example.com/a".to_string(),
+            page_result("https://example.com/a"),
+        );
+
+        let mut skipped = page_result("https://example.com/b");
+        skipped.skipped = Some("disallowed-by-robots-txt".to_string());
+        let current = vec![page_result("https://example.com/a"), skipped];
+
+        let diff = diff_against_previous(&previous, &current);
+        assert!(diff.added.is_empty());
+        assert!(diff.removed.is_empty());
+        assert!(diff.changed.is_empty());
+    }
+
+    #[test]
+    fn diff_against_previous_reports_changed_content_hash() {
+        let mut prev_result = page_result("https://example.com/a");
+        prev_result.content_hash = Some("aaa".to_string());
+        let mut previous = HashMap::new();
+        previous.insert("https://example.com/a".to_string(), prev_result);
+
+        let mut current_result = page_result("https://example.com/a");
+        current_result.content_hash = Some("bbb".to_string());
+
+        let diff = diff_against_previous(&previous, &[current_result]);
+        assert_eq!(diff.changed, vec!["https://example.com/a".to_string()]);
+    }
+
+    #[test]
+    fn unchanged_result_clears_skipped_on_a_live_revalidation() {
+        let mut prev = page_result("https://example.com/a");
+        prev.skipped = Some("filtered-out-by-modified-since-or-min-priority".to_string());
+
+        let result = unchanged_result(Some(prev), "https://example.com/a", 1);
+        assert_eq!(result.skipped, None);
+    }
+}